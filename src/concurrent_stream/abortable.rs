@@ -0,0 +1,168 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Shared state between an [`Abortable`] and its [`AbortHandle`].
+#[derive(Debug, Default)]
+struct AbortState {
+    aborted: AtomicBool,
+    // The waker from the most recent poll, so `abort` can wake the task even
+    // if it's currently parked waiting on something else entirely.
+    waker: Mutex<Option<Waker>>,
+}
+
+/// A handle to a in-flight [`Abortable`] future, used to cancel it from
+/// another task.
+///
+/// Created alongside an [`Abortable`] by wrapping the future returned from
+/// `for_each`/`try_for_each`/etc. with [`abortable`].
+#[derive(Debug, Clone)]
+pub struct AbortHandle {
+    state: Arc<AbortState>,
+}
+
+impl AbortHandle {
+    /// Cancel the paired [`Abortable`] future.
+    ///
+    /// The underlying consumer will stop accepting new futures from upstream
+    /// and drop everything it's currently holding at its next poll, rather
+    /// than waiting for the in-flight work to resolve. If the `Abortable` is
+    /// currently parked, it's woken immediately so that next poll happens
+    /// right away instead of waiting on whatever it was last polled for.
+    pub fn abort(&self) {
+        self.state.aborted.store(true, Ordering::Relaxed);
+        if let Some(waker) = self.state.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    /// Returns whether [`abort`](Self::abort) has been called.
+    pub fn is_aborted(&self) -> bool {
+        self.state.aborted.load(Ordering::Relaxed)
+    }
+}
+
+/// An error returned by an [`Abortable`] future when it was aborted before
+/// it could resolve to its normal output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aborted;
+
+/// A future that can be remotely cancelled via its paired [`AbortHandle`].
+///
+/// Created by wrapping the future returned from a `ConcurrentStream`
+/// consumer (e.g. `for_each`, `try_for_each`) with [`abortable`].
+#[pin_project::pin_project]
+pub struct Abortable<Fut> {
+    #[pin]
+    inner: Option<Fut>,
+    state: Arc<AbortState>,
+}
+
+impl<Fut: Future> Abortable<Fut> {
+    pub(crate) fn new(inner: Fut) -> (Self, AbortHandle) {
+        let state = Arc::new(AbortState::default());
+        let handle = AbortHandle {
+            state: state.clone(),
+        };
+        (
+            Self {
+                inner: Some(inner),
+                state,
+            },
+            handle,
+        )
+    }
+}
+
+impl<Fut: Future> Future for Abortable<Fut> {
+    type Output = Result<Fut::Output, Aborted>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        // Register our waker before checking `aborted`, so a concurrent call
+        // to `abort()` can never land between the check and the registration
+        // and go unnoticed until some unrelated wakeup happens to occur.
+        *this.state.waker.lock().unwrap() = Some(cx.waker().clone());
+
+        if this.state.aborted.load(Ordering::Relaxed) {
+            // Drop whatever work is still in flight - including everything
+            // held in the consumer's `FutureGroup` - instead of polling it
+            // to completion.
+            this.inner.set(None);
+            return Poll::Ready(Err(Aborted));
+        }
+
+        match this.inner.as_mut().as_pin_mut() {
+            Some(fut) => fut.poll(cx).map(Ok),
+            None => panic!("`Abortable` polled after it already resolved"),
+        }
+    }
+}
+
+/// Wrap `fut` so it can be cancelled from another task via the returned
+/// [`AbortHandle`].
+pub(crate) fn abortable<Fut: Future>(fut: Fut) -> (Abortable<Fut>, AbortHandle) {
+    Abortable::new(fut)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use futures_lite::future::yield_now;
+    use futures_lite::stream;
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn aborts_before_completion() {
+        futures_lite::future::block_on(async {
+            let count = Arc::new(AtomicUsize::new(0));
+            let (fut, handle) = abortable(stream::repeat(1).co().for_each({
+                let count = count.clone();
+                move |n| {
+                    let count = count.clone();
+                    async move {
+                        count.fetch_add(n, Ordering::Relaxed);
+                        yield_now().await;
+                    }
+                }
+            }));
+
+            handle.abort();
+            assert_eq!(fut.await, Err(Aborted));
+        });
+    }
+
+    #[test]
+    fn wakes_up_in_flight_work() {
+        futures_lite::future::block_on(async {
+            // A stream that never ends and never resolves on its own, so the
+            // only way `fut` ever completes is by being woken by `abort()`
+            // while it's genuinely parked, rather than by some unrelated
+            // wakeup racing ahead of it. Without a concurrency limit, `send`
+            // would keep inserting new never-resolving futures into the
+            // group on every call without ever awaiting any of them, so the
+            // driving future would spin forever instead of yielding
+            // `Poll::Pending` - `limit(1)` forces it to actually await the
+            // one in-flight future, so it genuinely parks.
+            let (fut, handle) = abortable(
+                stream::repeat(())
+                    .co()
+                    .limit(NonZeroUsize::new(1))
+                    .for_each(|()| std::future::pending::<()>()),
+            );
+
+            let aborter = async {
+                yield_now().await;
+                handle.abort();
+            };
+
+            let (result, ()) = futures_lite::future::zip(fut, aborter).await;
+            assert_eq!(result, Err(Aborted));
+        });
+    }
+}
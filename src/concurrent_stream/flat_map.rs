@@ -0,0 +1,286 @@
+use crate::future::FutureGroup;
+use futures_lite::future::yield_now;
+use futures_lite::StreamExt;
+
+use super::{ConcurrentStream, Consumer, ConsumerState};
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A concurrent stream that maps each item to a sub-stream, and flattens the
+/// output of all the sub-streams back into a single concurrent stream.
+///
+/// This `struct` is created by the [`flat_map`] method on [`ConcurrentStream`].
+/// See its documentation for more.
+///
+/// [`flat_map`]: trait.ConcurrentStream.html#method.flat_map
+#[derive(Debug)]
+pub struct FlatMap<CS, F> {
+    inner: CS,
+    f: F,
+}
+
+impl<CS, F> FlatMap<CS, F> {
+    pub(crate) fn new(inner: CS, f: F) -> Self {
+        Self { inner, f }
+    }
+}
+
+/// Flattens a concurrent stream of concurrent streams into a single
+/// concurrent stream of their items.
+///
+/// This `fn` backs the [`flatten`] method on [`ConcurrentStream`], and is
+/// exactly `flat_map(|s| s)`.
+///
+/// [`flatten`]: trait.ConcurrentStream.html#method.flatten
+pub(crate) fn flatten<CS>(inner: CS) -> FlatMap<CS, fn(CS::Item) -> CS::Item>
+where
+    CS: ConcurrentStream,
+    CS::Item: ConcurrentStream,
+{
+    FlatMap::new(inner, identity)
+}
+
+fn identity<T>(value: T) -> T {
+    value
+}
+
+impl<CS, F, CS2> ConcurrentStream for FlatMap<CS, F>
+where
+    CS: ConcurrentStream,
+    F: Fn(CS::Item) -> CS2,
+    F: Clone,
+    CS2: ConcurrentStream,
+{
+    type Item = CS2::Item;
+    type Future = CS2::Future;
+
+    async fn drive<C>(self, consumer: C) -> C::Output
+    where
+        C: Consumer<Self::Item, Self::Future>,
+    {
+        // Inner streams share the outer concurrency budget rather than each
+        // getting a fresh one: `count`/`limit` bound how many sub-streams
+        // are being driven to completion at once, across every upstream
+        // item, not just within a single one. The downstream consumer
+        // itself is shared (not cloned) across every sub-stream, behind a
+        // `Mutex` so items from whichever sub-stream is ready first can
+        // reach it without waiting for any other sub-stream to finish.
+        let limit = self
+            .inner
+            .concurrency_limit()
+            .map(|n| n.get())
+            .unwrap_or(usize::MAX);
+        self.inner
+            .drive(FlatMapConsumer {
+                inner: Arc::new(Mutex::new(consumer)),
+                f: self.f,
+                count: Arc::new(AtomicUsize::new(0)),
+                limit,
+                group: Box::pin(FutureGroup::new()),
+            })
+            .await
+    }
+
+    fn concurrency_limit(&self) -> Option<NonZeroUsize> {
+        self.inner.concurrency_limit()
+    }
+}
+
+/// A single sub-stream drive, boxed because `ConcurrentStream::drive` is an
+/// `async fn` in a trait and its returned future can't otherwise be named.
+/// Resolves to `Break` if the downstream consumer reported a `Break` to this
+/// sub-stream and to `Continue` otherwise, so a `Break` can propagate back
+/// out of the sub-stream that observed it.
+type DriveFut = Pin<Box<dyn Future<Output = ConsumerState>>>;
+
+struct FlatMapConsumer<C, F> {
+    inner: Arc<Mutex<C>>,
+    f: F,
+    count: Arc<AtomicUsize>,
+    limit: usize,
+    group: Pin<Box<FutureGroup<DriveFut>>>,
+}
+
+impl<C, F, UpstreamT, FutT, CS2> Consumer<UpstreamT, FutT> for FlatMapConsumer<C, F>
+where
+    FutT: Future<Output = UpstreamT> + 'static,
+    F: Fn(UpstreamT) -> CS2,
+    F: Clone + 'static,
+    CS2: ConcurrentStream + 'static,
+    C: Consumer<CS2::Item, CS2::Future> + 'static,
+{
+    type Output = C::Output;
+
+    async fn send(&mut self, future: FutT) -> ConsumerState {
+        // If we have no space, drain completed sub-stream drives until space
+        // opens up, instead of blocking on the one we're about to insert.
+        // This is what lets multiple upstream items' sub-streams run
+        // concurrently rather than strictly one at a time.
+        while self.count.load(Ordering::Relaxed) >= self.limit {
+            match self.group.next().await {
+                Some(ConsumerState::Break) => return ConsumerState::Break,
+                Some(_) => {}
+                None => break,
+            }
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let f = self.f.clone();
+        let inner = self.inner.clone();
+        let count = self.count.clone();
+        let fut: DriveFut = Box::pin(async move {
+            let upstream_item = future.await;
+            let inner_stream = f(upstream_item);
+            let state = inner_stream
+                .drive(Forward {
+                    inner,
+                    broke: false,
+                })
+                .await;
+            count.fetch_sub(1, Ordering::Relaxed);
+            state
+        });
+        self.group.as_mut().insert_pinned(fut);
+
+        ConsumerState::Continue
+    }
+
+    async fn progress(&mut self) -> ConsumerState {
+        while let Some(state) = self.group.next().await {
+            if let ConsumerState::Break = state {
+                return ConsumerState::Break;
+            }
+        }
+        ConsumerState::Empty
+    }
+
+    async fn finish(mut self) -> Self::Output {
+        while let Some(state) = self.group.next().await {
+            if let ConsumerState::Break = state {
+                break;
+            }
+        }
+        // Drop any sub-stream drives still in flight (a `Break` ends the loop
+        // above early). Every `Forward` has then been dropped, so we're the
+        // sole owner of `inner`.
+        drop(self.group);
+        let inner = Arc::try_unwrap(self.inner)
+            .ok()
+            .expect("no sub-stream drive is still holding the shared consumer")
+            .into_inner()
+            .expect("the shared consumer's mutex was never poisoned");
+        inner.finish().await
+    }
+}
+
+/// Forwards a single sub-stream's sends directly into the shared downstream
+/// consumer, so its items are visible as soon as *that* sub-stream produces
+/// them rather than only once the whole sub-stream has finished.
+///
+/// The downstream consumer is shared by every sub-stream being flattened, so
+/// access to it is mediated by a `Mutex`. We only ever `try_lock` it and
+/// yield on contention rather than blocking - since everything here runs
+/// cooperatively on a single task, blocking on a lock already held by a
+/// *different* sub-stream's `Forward` (polled from the very same task) would
+/// deadlock.
+struct Forward<C> {
+    inner: Arc<Mutex<C>>,
+    broke: bool,
+}
+
+impl<C, Item, Fut> Consumer<Item, Fut> for Forward<C>
+where
+    Fut: Future<Output = Item>,
+    C: Consumer<Item, Fut>,
+{
+    type Output = ConsumerState;
+
+    async fn send(&mut self, future: Fut) -> ConsumerState {
+        if self.broke {
+            return ConsumerState::Break;
+        }
+        loop {
+            if let Ok(mut guard) = self.inner.try_lock() {
+                let state = guard.send(future).await;
+                if let ConsumerState::Break = state {
+                    self.broke = true;
+                }
+                return state;
+            }
+            yield_now().await;
+        }
+    }
+
+    async fn progress(&mut self) -> ConsumerState {
+        loop {
+            if let Ok(mut guard) = self.inner.try_lock() {
+                let state = guard.progress().await;
+                if let ConsumerState::Break = state {
+                    self.broke = true;
+                }
+                return state;
+            }
+            yield_now().await;
+        }
+    }
+
+    async fn finish(self) -> Self::Output {
+        // The real `finish()` belongs solely to the outer `FlatMapConsumer`,
+        // once every sub-stream has been driven - this one just reports
+        // whether *this* sub-stream observed a `Break`.
+        if self.broke {
+            ConsumerState::Break
+        } else {
+            ConsumerState::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use futures_lite::stream;
+
+    #[test]
+    fn flattens_sub_streams() {
+        futures_lite::future::block_on(async {
+            let sum = Arc::new(AtomicUsize::new(0));
+            stream::iter(0..4)
+                .co()
+                .flat_map(|n| stream::iter(0..n).co())
+                .for_each(|n| {
+                    let sum = sum.clone();
+                    async move {
+                        sum.fetch_add(n, Ordering::Relaxed);
+                    }
+                })
+                .await;
+
+            // sum(0..0) + sum(0..1) + sum(0..2) + sum(0..3) == 0 + 0 + 1 + 3
+            assert_eq!(sum.load(Ordering::Relaxed), 4);
+        });
+    }
+
+    #[test]
+    fn flatten_streams_of_streams() {
+        futures_lite::future::block_on(async {
+            let sum = Arc::new(AtomicUsize::new(0));
+            stream::iter([stream::iter(0..3).co(), stream::iter(3..5).co()])
+                .co()
+                .flatten()
+                .for_each(|n| {
+                    let sum = sum.clone();
+                    async move {
+                        sum.fetch_add(n, Ordering::Relaxed);
+                    }
+                })
+                .await;
+
+            assert_eq!(sum.load(Ordering::Relaxed), 0 + 1 + 2 + 3 + 4);
+        });
+    }
+}
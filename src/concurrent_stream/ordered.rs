@@ -0,0 +1,260 @@
+use crate::future::FutureGroup;
+use futures_lite::StreamExt;
+
+use super::{Consumer, ConsumerState};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::future::{ready, Future, Ready};
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{ready as poll_ready, Context, Poll};
+
+/// An item tagged with the sequence number it arrived from upstream in.
+///
+/// Ordering is entirely determined by `seq`, which lets us stash completed
+/// items in a [`BinaryHeap`] and pop them back out in arrival order.
+struct Seq<T> {
+    seq: u64,
+    value: T,
+}
+
+impl<T> PartialEq for Seq<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq == other.seq
+    }
+}
+impl<T> Eq for Seq<T> {}
+impl<T> PartialOrd for Seq<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<T> Ord for Seq<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.seq.cmp(&other.seq)
+    }
+}
+
+pub(crate) struct OrderedConsumer<C, T, FutT>
+where
+    FutT: Future<Output = T>,
+    C: Consumer<T, Ready<T>>,
+{
+    // NOTE: we can remove the `Arc` here if we're willing to make this struct self-referential
+    count: Arc<AtomicUsize>,
+    // TODO: remove the `Pin<Box>` from this signature by requiring this struct is pinned
+    group: Pin<Box<FutureGroup<SeqFut<FutT, T>>>>,
+    limit: usize,
+    /// How many completed-but-not-yet-emitted items we're willing to hold in
+    /// `reorder_buf` before applying backpressure to upstream. An early item
+    /// that is slow to resolve otherwise lets this buffer grow unbounded.
+    reorder_limit: usize,
+    next_seq: u64,
+    next_to_emit: u64,
+    reorder_buf: BinaryHeap<Reverse<Seq<T>>>,
+    inner: C,
+}
+
+impl<C, T, FutT> OrderedConsumer<C, T, FutT>
+where
+    FutT: Future<Output = T>,
+    C: Consumer<T, Ready<T>>,
+{
+    pub(crate) fn new(
+        inner: C,
+        limit: Option<NonZeroUsize>,
+        reorder_limit: Option<NonZeroUsize>,
+    ) -> Self {
+        let limit = match limit {
+            Some(n) => n.get(),
+            None => usize::MAX,
+        };
+        let reorder_limit = match reorder_limit {
+            Some(n) => n.get(),
+            None => usize::MAX,
+        };
+        Self {
+            limit,
+            reorder_limit,
+            inner,
+            next_seq: 0,
+            next_to_emit: 0,
+            count: Arc::new(AtomicUsize::new(0)),
+            group: Box::pin(FutureGroup::new()),
+            reorder_buf: BinaryHeap::new(),
+        }
+    }
+
+    /// Forward every item at the front of `reorder_buf` that is next in line
+    /// to the wrapped consumer, draining consecutively for as long as we can.
+    async fn drain_in_order(&mut self) -> ConsumerState {
+        while let Some(Reverse(seq)) = self.reorder_buf.peek() {
+            if seq.seq != self.next_to_emit {
+                break;
+            }
+            let Reverse(seq) = self.reorder_buf.pop().unwrap();
+            self.next_to_emit += 1;
+            if let ConsumerState::Break = self.inner.send(ready(seq.value)).await {
+                return ConsumerState::Break;
+            }
+        }
+        ConsumerState::Continue
+    }
+
+    /// Handle a single completed item from the group: either forward it
+    /// straight away if it's next in line, or stash it for later.
+    async fn handle_completed(&mut self, seq: Seq<T>) -> ConsumerState {
+        if seq.seq == self.next_to_emit {
+            self.next_to_emit += 1;
+            if let ConsumerState::Break = self.inner.send(ready(seq.value)).await {
+                return ConsumerState::Break;
+            }
+            self.drain_in_order().await
+        } else {
+            self.reorder_buf.push(Reverse(seq));
+            ConsumerState::Continue
+        }
+    }
+}
+
+// OK: validated! - we push the resolved item into the next consumer, wrapped
+// in a `Ready` future so the sequence information we've already resolved
+// isn't lost.
+impl<C, T, FutT> Consumer<T, FutT> for OrderedConsumer<C, T, FutT>
+where
+    FutT: Future<Output = T>,
+    C: Consumer<T, Ready<T>>,
+{
+    type Output = C::Output;
+
+    async fn send(&mut self, future: FutT) -> ConsumerState {
+        // Apply backpressure both on the number of in-flight futures, and on
+        // how many completed-but-unemitted items we're holding onto.
+        while self.count.load(Ordering::Relaxed) >= self.limit
+            || self.reorder_buf.len() >= self.reorder_limit
+        {
+            match self.group.next().await {
+                Some(seq) => {
+                    if let ConsumerState::Break = self.handle_completed(seq).await {
+                        return ConsumerState::Break;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let fut = SeqFut::new(future, seq, self.count.clone());
+        self.group.as_mut().insert_pinned(fut);
+
+        ConsumerState::Continue
+    }
+
+    async fn progress(&mut self) -> ConsumerState {
+        while let Some(seq) = self.group.next().await {
+            if let ConsumerState::Break = self.handle_completed(seq).await {
+                return ConsumerState::Break;
+            }
+        }
+        ConsumerState::Empty
+    }
+
+    async fn finish(mut self) -> Self::Output {
+        while let Some(seq) = self.group.next().await {
+            if let ConsumerState::Break = self.handle_completed(seq).await {
+                break;
+            }
+        }
+        self.inner.finish().await
+    }
+}
+
+/// Tags a future's output with the sequence number it was received in, so
+/// the [`OrderedConsumer`] can re-sort completions back into arrival order.
+#[derive(Debug)]
+pub struct SeqFut<FutT, T>
+where
+    FutT: Future<Output = T>,
+{
+    done: bool,
+    count: Arc<AtomicUsize>,
+    seq: u64,
+    fut: FutT,
+}
+
+impl<FutT, T> SeqFut<FutT, T>
+where
+    FutT: Future<Output = T>,
+{
+    fn new(fut: FutT, seq: u64, count: Arc<AtomicUsize>) -> Self {
+        Self {
+            done: false,
+            count,
+            seq,
+            fut,
+        }
+    }
+}
+
+impl<FutT, T> Future for SeqFut<FutT, T>
+where
+    FutT: Future<Output = T>,
+{
+    type Output = Seq<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: we need to access the inner future's fields to project them
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.done {
+            panic!("future has already been polled to completion once");
+        }
+
+        // SAFETY: we're pin projecting here
+        let value = poll_ready!(unsafe { Pin::new_unchecked(&mut this.fut) }.poll(cx));
+        this.count.fetch_sub(1, Ordering::Relaxed);
+        this.done = true;
+        Poll::Ready(Seq {
+            seq: this.seq,
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use futures_lite::future::yield_now;
+    use futures_lite::stream;
+
+    #[test]
+    fn preserves_input_order() {
+        futures_lite::future::block_on(async {
+            let out = Arc::new(std::sync::Mutex::new(Vec::new()));
+            stream::iter(0..10)
+                .co()
+                .limit(NonZeroUsize::new(4))
+                .ordered()
+                .for_each(|n| {
+                    let out = out.clone();
+                    async move {
+                        // Make earlier items resolve slower than later ones,
+                        // so without reordering we'd observe them out of order.
+                        if n % 2 == 0 {
+                            for _ in 0..4 {
+                                yield_now().await;
+                            }
+                        }
+                        out.lock().unwrap().push(n);
+                    }
+                })
+                .await;
+
+            assert_eq!(*out.lock().unwrap(), (0..10).collect::<Vec<_>>());
+        });
+    }
+}
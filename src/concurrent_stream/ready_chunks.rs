@@ -0,0 +1,219 @@
+use crate::future::FutureGroup;
+use futures_lite::StreamExt;
+
+use super::{ConcurrentStream, Consumer, ConsumerState};
+use std::future::{ready, Future, Ready};
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{ready as poll_ready, Context, Poll};
+
+/// A concurrent stream that batches its items into `Vec`s of up to `cap`
+/// items before handing them downstream.
+///
+/// This `struct` is created by the [`ready_chunks`] method on [`ConcurrentStream`].
+/// See its documentation for more.
+///
+/// [`ready_chunks`]: trait.ConcurrentStream.html#method.ready_chunks
+#[derive(Debug)]
+pub struct ReadyChunks<CS: ConcurrentStream> {
+    inner: CS,
+    cap: NonZeroUsize,
+}
+
+impl<CS: ConcurrentStream> ReadyChunks<CS> {
+    pub(crate) fn new(inner: CS, cap: NonZeroUsize) -> Self {
+        Self { inner, cap }
+    }
+}
+
+impl<CS: ConcurrentStream> ConcurrentStream for ReadyChunks<CS> {
+    type Item = Vec<CS::Item>;
+    type Future = Ready<Vec<CS::Item>>;
+
+    async fn drive<C>(self, consumer: C) -> C::Output
+    where
+        C: Consumer<Self::Item, Self::Future>,
+    {
+        let limit = self
+            .inner
+            .concurrency_limit()
+            .map(|n| n.get())
+            .unwrap_or(usize::MAX);
+        self.inner
+            .drive(ReadyChunksConsumer {
+                inner: consumer,
+                cap: self.cap,
+                limit,
+                buf: Vec::new(),
+                count: Arc::new(AtomicUsize::new(0)),
+                group: Box::pin(FutureGroup::new()),
+            })
+            .await
+    }
+
+    fn concurrency_limit(&self) -> Option<NonZeroUsize> {
+        self.inner.concurrency_limit()
+    }
+}
+
+struct ReadyChunksConsumer<C, Item, FutT>
+where
+    FutT: Future<Output = Item>,
+    C: Consumer<Vec<Item>, Ready<Vec<Item>>>,
+{
+    inner: C,
+    cap: NonZeroUsize,
+    limit: usize,
+    buf: Vec<Item>,
+    count: Arc<AtomicUsize>,
+    group: Pin<Box<FutureGroup<CountedFut<FutT, Item>>>>,
+}
+
+impl<C, Item, FutT> ReadyChunksConsumer<C, Item, FutT>
+where
+    FutT: Future<Output = Item>,
+    C: Consumer<Vec<Item>, Ready<Vec<Item>>>,
+{
+    /// Push a freshly completed item into the pending batch, flushing it
+    /// downstream as soon as it reaches `cap`. A batch that has not reached
+    /// `cap` is not flushed here; only `finish` hands a partial final batch
+    /// downstream.
+    async fn push(&mut self, item: Item) -> ConsumerState {
+        self.buf.push(item);
+        if self.buf.len() >= self.cap.get() {
+            let batch = std::mem::replace(&mut self.buf, Vec::with_capacity(self.cap.get()));
+            return self.inner.send(ready(batch)).await;
+        }
+        ConsumerState::Continue
+    }
+}
+
+impl<C, Item, FutT> Consumer<Item, FutT> for ReadyChunksConsumer<C, Item, FutT>
+where
+    FutT: Future<Output = Item>,
+    C: Consumer<Vec<Item>, Ready<Vec<Item>>>,
+{
+    type Output = C::Output;
+
+    async fn send(&mut self, future: FutT) -> ConsumerState {
+        // If we have no space, we're going to provide backpressure until we
+        // have space - this is what bounds the concurrency of the futures
+        // producing items, as opposed to the (already-resolved) batches we
+        // hand downstream.
+        while self.count.load(Ordering::Relaxed) >= self.limit {
+            match self.group.next().await {
+                Some(item) => {
+                    if let ConsumerState::Break = self.push(item).await {
+                        return ConsumerState::Break;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let fut = CountedFut::new(future, self.count.clone());
+        self.group.as_mut().insert_pinned(fut);
+        ConsumerState::Continue
+    }
+
+    async fn progress(&mut self) -> ConsumerState {
+        while let Some(item) = self.group.next().await {
+            if let ConsumerState::Break = self.push(item).await {
+                return ConsumerState::Break;
+            }
+        }
+        ConsumerState::Empty
+    }
+
+    async fn finish(mut self) -> Self::Output {
+        let mut broken = false;
+        while let Some(item) = self.group.next().await {
+            if let ConsumerState::Break = self.push(item).await {
+                broken = true;
+                break;
+            }
+        }
+        // Once the downstream consumer has signaled `Break`, no further
+        // sends are allowed - including the trailing partial batch.
+        if !broken && !self.buf.is_empty() {
+            self.inner.send(ready(self.buf)).await;
+        }
+        self.inner.finish().await
+    }
+}
+
+/// Wraps an upstream future so we can track how many are still in flight.
+#[derive(Debug)]
+struct CountedFut<FutT, T>
+where
+    FutT: Future<Output = T>,
+{
+    done: bool,
+    count: Arc<AtomicUsize>,
+    fut: FutT,
+}
+
+impl<FutT, T> CountedFut<FutT, T>
+where
+    FutT: Future<Output = T>,
+{
+    fn new(fut: FutT, count: Arc<AtomicUsize>) -> Self {
+        Self {
+            done: false,
+            count,
+            fut,
+        }
+    }
+}
+
+impl<FutT, T> Future for CountedFut<FutT, T>
+where
+    FutT: Future<Output = T>,
+{
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: we need to access the inner future's fields to project them
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.done {
+            panic!("future has already been polled to completion once");
+        }
+
+        // SAFETY: we're pin projecting here
+        let value = poll_ready!(unsafe { Pin::new_unchecked(&mut this.fut) }.poll(cx));
+        this.count.fetch_sub(1, Ordering::Relaxed);
+        this.done = true;
+        Poll::Ready(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use futures_lite::stream;
+
+    #[test]
+    fn batches_up_to_cap() {
+        futures_lite::future::block_on(async {
+            let batches = Arc::new(std::sync::Mutex::new(Vec::new()));
+            stream::iter(0..10)
+                .co()
+                .ready_chunks(NonZeroUsize::new(3).unwrap())
+                .for_each(|batch: Vec<i32>| {
+                    let batches = batches.clone();
+                    async move {
+                        batches.lock().unwrap().push(batch);
+                    }
+                })
+                .await;
+
+            let batches = batches.lock().unwrap();
+            assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 10);
+            assert!(batches.iter().all(|b| b.len() <= 3));
+        });
+    }
+}
@@ -0,0 +1,243 @@
+use crate::future::FutureGroup;
+use futures_lite::StreamExt;
+
+use super::{Consumer, ConsumerState};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::num::NonZeroUsize;
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+pub(crate) struct TryForEachConsumer<FutT, T, F, FutB, E>
+where
+    FutT: Future<Output = T>,
+    F: Fn(T) -> FutB,
+    FutB: Future<Output = Result<(), E>>,
+{
+    // NOTE: we can remove the `Arc` here if we're willing to make this struct self-referential
+    count: Arc<AtomicUsize>,
+    // TODO: remove the `Pin<Box>` from this signature by requiring this struct is pinned
+    group: Pin<Box<FutureGroup<TryForEachFut<F, FutT, T, FutB, E>>>>,
+    limit: usize,
+    error: Option<E>,
+    f: F,
+    _phantom: PhantomData<(T, FutB)>,
+}
+
+impl<A, T, F, B, E> TryForEachConsumer<A, T, F, B, E>
+where
+    A: Future<Output = T>,
+    F: Fn(T) -> B,
+    B: Future<Output = Result<(), E>>,
+{
+    pub(crate) fn new(limit: Option<NonZeroUsize>, f: F) -> Self {
+        let limit = match limit {
+            Some(n) => n.get(),
+            None => usize::MAX,
+        };
+        Self {
+            limit,
+            f,
+            error: None,
+            _phantom: PhantomData,
+            count: Arc::new(AtomicUsize::new(0)),
+            group: Box::pin(FutureGroup::new()),
+        }
+    }
+}
+
+// OK: validated! - this is a terminal consumer: it keeps the first
+// `Result::Err` from the closures' futures and returns it from `finish`
+impl<FutT, T, F, B, E> Consumer<T, FutT> for TryForEachConsumer<FutT, T, F, B, E>
+where
+    FutT: Future<Output = T>,
+    F: Fn(T) -> B,
+    F: Clone,
+    B: Future<Output = Result<(), E>>,
+{
+    type Output = Result<(), E>;
+
+    async fn send(&mut self, future: FutT) -> ConsumerState {
+        // We've already observed an error from a previously completed future;
+        // stop accepting new work so the driver halts enumeration.
+        if self.error.is_some() {
+            return ConsumerState::Break;
+        }
+
+        // If we have no space, we're going to provide backpressure until we have space
+        while self.count.load(Ordering::Relaxed) >= self.limit {
+            if let Some(Err(err)) = self.group.next().await {
+                self.error = Some(err);
+                return ConsumerState::Break;
+            }
+        }
+
+        // Space was available! - insert the item for posterity
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let fut = TryForEachFut::new(self.f.clone(), future, self.count.clone());
+        self.group.as_mut().insert_pinned(fut);
+
+        ConsumerState::Continue
+    }
+
+    async fn progress(&mut self) -> ConsumerState {
+        while let Some(res) = self.group.next().await {
+            if let Err(err) = res {
+                // Only the first error is ever reported; later errors from
+                // futures that were already in flight are discarded.
+                self.error.get_or_insert(err);
+                return ConsumerState::Break;
+            }
+        }
+        ConsumerState::Empty
+    }
+
+    async fn finish(mut self) -> Self::Output {
+        // Wait until all the futures in the group have resolved, unless we've
+        // already observed an error - in which case we drop whatever is still
+        // in flight rather than awaiting it.
+        if self.error.is_none() {
+            while let Some(res) = self.group.next().await {
+                if let Err(err) = res {
+                    self.error = Some(err);
+                    break;
+                }
+            }
+        }
+
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Takes a future and maps it to another future via a closure
+#[derive(Debug)]
+pub struct TryForEachFut<F, FutT, T, FutB, E>
+where
+    FutT: Future<Output = T>,
+    F: Fn(T) -> FutB,
+    FutB: Future<Output = Result<(), E>>,
+{
+    done: bool,
+    count: Arc<AtomicUsize>,
+    f: F,
+    fut_t: Option<FutT>,
+    fut_b: Option<FutB>,
+}
+
+impl<F, FutT, T, FutB, E> TryForEachFut<F, FutT, T, FutB, E>
+where
+    FutT: Future<Output = T>,
+    F: Fn(T) -> FutB,
+    FutB: Future<Output = Result<(), E>>,
+{
+    fn new(f: F, fut_t: FutT, count: Arc<AtomicUsize>) -> Self {
+        Self {
+            done: false,
+            count,
+            f,
+            fut_t: Some(fut_t),
+            fut_b: None,
+        }
+    }
+}
+
+impl<F, FutT, T, FutB, E> Future for TryForEachFut<F, FutT, T, FutB, E>
+where
+    FutT: Future<Output = T>,
+    F: Fn(T) -> FutB,
+    FutB: Future<Output = Result<(), E>>,
+{
+    type Output = Result<(), E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: we need to access the inner future's fields to project them
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.done {
+            panic!("future has already been polled to completion once");
+        }
+
+        // Poll forward the future containing the value of `T`
+        if let Some(fut) = this.fut_t.as_mut() {
+            // SAFETY: we're pin projecting here
+            let t = ready!(unsafe { Pin::new_unchecked(fut) }.poll(cx));
+            let fut_b = (this.f)(t);
+            this.fut_t = None;
+            this.fut_b = Some(fut_b);
+        }
+
+        // Poll forward the future returned by the closure
+        if let Some(fut) = this.fut_b.as_mut() {
+            // SAFETY: we're pin projecting here
+            let res = ready!(unsafe { Pin::new_unchecked(fut) }.poll(cx));
+            this.count.fetch_sub(1, Ordering::Relaxed);
+            this.done = true;
+            return Poll::Ready(res);
+        }
+
+        unreachable!("neither future `a` nor future `b` were ready");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use futures_lite::stream;
+    use std::sync::Arc;
+
+    #[test]
+    fn all_ok() {
+        futures_lite::future::block_on(async {
+            let count = Arc::new(AtomicUsize::new(0));
+            let res = stream::repeat(1)
+                .take(10)
+                .co()
+                .limit(NonZeroUsize::new(3))
+                .try_for_each(|n| {
+                    let count = count.clone();
+                    async move {
+                        count.fetch_add(n, Ordering::Relaxed);
+                        Ok::<(), &'static str>(())
+                    }
+                })
+                .await;
+
+            assert_eq!(res, Ok(()));
+            assert_eq!(count.load(Ordering::Relaxed), 10);
+        });
+    }
+
+    #[test]
+    fn short_circuits_on_first_error() {
+        futures_lite::future::block_on(async {
+            let seen = Arc::new(AtomicUsize::new(0));
+            let res = stream::repeat(1)
+                .take(10)
+                .co()
+                .limit(NonZeroUsize::new(1))
+                .try_for_each(|_n| {
+                    let seen = seen.clone();
+                    async move {
+                        let prev = seen.fetch_add(1, Ordering::Relaxed);
+                        if prev == 2 {
+                            Err("boom")
+                        } else {
+                            Ok(())
+                        }
+                    }
+                })
+                .await;
+
+            assert_eq!(res, Err("boom"));
+            // we should not have kept going after the error was observed: with
+            // a limit of 1 the third call fails, and no fourth call is made
+            assert_eq!(seen.load(Ordering::Relaxed), 3);
+        });
+    }
+}
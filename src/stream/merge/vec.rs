@@ -7,21 +7,68 @@ use futures_core::Stream;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+/// A strategy for choosing which of [`Merge`]'s streams should be polled
+/// first on a given wakeup.
+///
+/// `select` is handed the number of streams currently being merged, and
+/// returns the preferred index to poll first. If that stream is `Pending` or
+/// has already ended, `Merge` falls back to scanning the rest in order
+/// starting from the preferred index.
+///
+/// The returned index does not need to be `< len` - for example a strict
+/// round-robin strategy may prefer to keep an ever-incrementing counter
+/// rather than re-wrapping it on every call. `Merge` takes the result modulo
+/// `len` itself before using it.
+pub trait MergeStrategy {
+    /// Selects the index that should be polled first, given `len` streams.
+    fn select(&mut self, len: usize) -> usize;
+}
+
+impl<F> MergeStrategy for F
+where
+    F: FnMut(usize) -> usize,
+{
+    fn select(&mut self, len: usize) -> usize {
+        (self)(len)
+    }
+}
+
+/// The default [`MergeStrategy`] used by [`merge`]: picks a uniformly random
+/// start index on every poll, which gives every stream a fair chance to be
+/// polled first.
+///
+/// [`merge`]: trait.Merge.html#method.merge
+#[derive(Debug)]
+pub struct RandomStrategy(RandomGenerator);
+
+impl RandomStrategy {
+    fn new() -> Self {
+        Self(RandomGenerator::new())
+    }
+}
+
+impl MergeStrategy for RandomStrategy {
+    fn select(&mut self, len: usize) -> usize {
+        self.0.random(len as u32) as usize
+    }
+}
+
 /// A stream that merges multiple streams into a single stream.
 ///
-/// This `struct` is created by the [`merge`] method on the [`Merge`] trait. See its
-/// documentation for more.
+/// This `struct` is created by the [`merge`] method on the [`Merge`] trait, or
+/// by [`Merge::merge_with_strategy`] for a custom polling order. See the
+/// trait documentation for more.
 ///
 /// [`merge`]: trait.Merge.html#method.merge
 /// [`Merge`]: trait.Merge.html
 #[pin_project::pin_project]
-pub struct Merge<S>
+pub struct Merge<S, Strat = RandomStrategy>
 where
     S: Stream,
 {
     #[pin]
     streams: Vec<Fuse<S>>,
-    rng: RandomGenerator,
+    strategy: Strat,
 }
 
 impl<S> Merge<S>
@@ -29,14 +76,32 @@ where
     S: Stream,
 {
     pub(crate) fn new(streams: Vec<S>) -> Self {
+        Self::merge_with_strategy(streams, RandomStrategy::new())
+    }
+}
+
+impl<S, Strat> Merge<S, Strat>
+where
+    S: Stream,
+    Strat: MergeStrategy,
+{
+    /// Create a new `Merge` stream which polls its inner streams in the
+    /// order decided by `strategy`, rather than the default uniformly-random
+    /// fairness.
+    ///
+    /// This is the entry point for callers who want a custom polling order -
+    /// e.g. always preferring one stream (priority merge), strict
+    /// round-robin, or weighted selection - instead of the fairness `merge`
+    /// provides by default.
+    pub fn merge_with_strategy(streams: Vec<S>, strategy: Strat) -> Self {
         Self {
             streams: streams.into_iter().map(Fuse::new).collect(),
-            rng: RandomGenerator::new(),
+            strategy,
         }
     }
 }
 
-impl<S> fmt::Debug for Merge<S>
+impl<S, Strat> fmt::Debug for Merge<S, Strat>
 where
     S: Stream + fmt::Debug,
 {
@@ -45,9 +110,10 @@ where
     }
 }
 
-impl<S> Stream for Merge<S>
+impl<S, Strat> Stream for Merge<S, Strat>
 where
     S: Stream,
+    Strat: MergeStrategy,
 {
     type Item = S::Item;
 
@@ -57,10 +123,13 @@ where
         // Iterate over our streams one-by-one. If a stream yields a value,
         // we exit early. By default we'll return `Poll::Ready(None)`, but
         // this changes if we encounter a `Poll::Pending`.
-        let random = this.rng.random(this.streams.len() as u32) as usize;
+        // `select` isn't required to return an in-range index (e.g. a strict
+        // round-robin strategy may prefer an ever-incrementing counter), so
+        // fold it back into range ourselves before using it.
+        let preferred = this.strategy.select(this.streams.len()) % this.streams.len().max(1);
         let mut res = Poll::Ready(None);
         for index in 0..this.streams.len() {
-            let index = (random + index).wrapping_rem(this.streams.len());
+            let index = (preferred + index).wrapping_rem(this.streams.len());
             let stream = utils::get_pin_mut_from_vec(this.streams.as_mut(), index).unwrap();
             match stream.poll_next(cx) {
                 Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
@@ -107,4 +176,23 @@ mod tests {
             assert_eq!(counter, 10);
         })
     }
+
+    #[test]
+    fn merge_with_strategy_always_prefers_first() {
+        block_on(async {
+            // With a strategy that always prefers stream 0, and a stream 0
+            // that never ends, the merged stream should never observe any
+            // items from the other streams.
+            let streams = vec![
+                stream::repeat(0).boxed(),
+                stream::once(1).boxed(),
+                stream::once(2).boxed(),
+            ];
+            let mut s = Merge::merge_with_strategy(streams, |_len: usize| 0);
+
+            for _ in 0..10 {
+                assert_eq!(s.next().await, Some(0));
+            }
+        })
+    }
 }